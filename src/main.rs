@@ -1,12 +1,13 @@
-use std::collections::{BTreeSet, VecDeque};
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
 use std::fs;
 use std::path::PathBuf;
 
 use sqlparser::ast::*;
 use sqlparser::dialect::{Dialect, GenericDialect, MsSqlDialect, MySqlDialect, PostgreSqlDialect, SnowflakeDialect, BigQueryDialect, SQLiteDialect, HiveDialect, AnsiDialect, RedshiftSqlDialect};
 use sqlparser::parser::Parser;
+use sqlparser::tokenizer::Tokenizer;
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 enum DialectKind {
     Generic,
     Postgres,
@@ -20,8 +21,53 @@ enum DialectKind {
     Redshift,
 }
 
-fn parse_sql_with_dialect(sql: &str, dialect: DialectKind) -> Result<Vec<Statement>, String> {
-    let dialect_impl: Box<dyn Dialect> = match dialect {
+// `--dialect auto` が全方言を順に試すときに使う一覧
+const ALL_DIALECTS: [DialectKind; 10] = [
+    DialectKind::Generic,
+    DialectKind::Postgres,
+    DialectKind::MySql,
+    DialectKind::MsSql,
+    DialectKind::Snowflake,
+    DialectKind::BigQuery,
+    DialectKind::SQLite,
+    DialectKind::Hive,
+    DialectKind::Ansi,
+    DialectKind::Redshift,
+];
+
+fn dialect_from_str(v: &str) -> Result<DialectKind, String> {
+    match v.to_lowercase().as_str() {
+        "generic" => Ok(DialectKind::Generic),
+        "postgres" | "postgresql" => Ok(DialectKind::Postgres),
+        "mysql" => Ok(DialectKind::MySql),
+        "mssql" => Ok(DialectKind::MsSql),
+        "snowflake" => Ok(DialectKind::Snowflake),
+        "bigquery" => Ok(DialectKind::BigQuery),
+        "sqlite" => Ok(DialectKind::SQLite),
+        "hive" => Ok(DialectKind::Hive),
+        "ansi" => Ok(DialectKind::Ansi),
+        "redshift" => Ok(DialectKind::Redshift),
+        _ => Err(format!("未知のdialect: {}", v)),
+    }
+}
+
+fn dialect_name(dialect: DialectKind) -> &'static str {
+    match dialect {
+        DialectKind::Generic => "generic",
+        DialectKind::Postgres => "postgres",
+        DialectKind::MySql => "mysql",
+        DialectKind::MsSql => "mssql",
+        DialectKind::Snowflake => "snowflake",
+        DialectKind::BigQuery => "bigquery",
+        DialectKind::SQLite => "sqlite",
+        DialectKind::Hive => "hive",
+        DialectKind::Ansi => "ansi",
+        DialectKind::Redshift => "redshift",
+    }
+}
+
+fn build_dialect_impl(dialect: DialectKind) -> Box<dyn Dialect> {
+    match dialect {
         DialectKind::Generic => Box::new(GenericDialect {}),
         DialectKind::Postgres => Box::new(PostgreSqlDialect {}),
         DialectKind::MySql => Box::new(MySqlDialect {}),
@@ -32,35 +78,106 @@ fn parse_sql_with_dialect(sql: &str, dialect: DialectKind) -> Result<Vec<Stateme
         DialectKind::Hive => Box::new(HiveDialect {}),
         DialectKind::Ansi => Box::new(AnsiDialect {}),
         DialectKind::Redshift => Box::new(RedshiftSqlDialect {}),
-    };
+    }
+}
+
+fn parse_sql_with_dialect(sql: &str, dialect: DialectKind) -> Result<Vec<Statement>, String> {
+    let dialect_impl = build_dialect_impl(dialect);
     Parser::parse_sql(&*dialect_impl, sql).map_err(|e| e.to_string())
 }
 
-fn collect_tables_from_query(query: &Query, out: &mut BTreeSet<String>) {
-    // 探索用のキュー（FROM句・JOIN・サブクエリ・CTE・セット演算など）
-    let mut queue: VecDeque<SetExpr> = VecDeque::new();
-    queue.push_back((*query.body).clone());
+// テーブルがクエリの中でどう使われているか（読み取り/書き込み）
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum TableRole {
+    Read,
+    Write,
+    ReadWrite,
+}
 
-    // WITH (CTE)
+impl TableRole {
+    fn merge(self, other: TableRole) -> TableRole {
+        use TableRole::*;
+        match (self, other) {
+            (Read, Read) => Read,
+            (Write, Write) => Write,
+            _ => ReadWrite,
+        }
+    }
+}
+
+impl std::fmt::Display for TableRole {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let s = match self {
+            TableRole::Read => "read",
+            TableRole::Write => "write",
+            TableRole::ReadWrite => "read+write",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+// 抽出結果一式: 物理テーブル（役割つき）、登場したCTE名、派生オブジェクトの依存関係
+#[derive(Debug, Default)]
+struct Extraction {
+    tables: BTreeMap<String, TableRole>,
+    ctes: BTreeSet<String>,
+    // (derived_object, source_table) : ビューやCTASの対象が読んでいる物理テーブル
+    edges: Vec<(String, String)>,
+}
+
+// 同じテーブルが複数回、異なる役割で現れた場合はマージする
+fn insert_role(out: &mut BTreeMap<String, TableRole>, name: String, role: TableRole) {
+    out.entry(name)
+        .and_modify(|existing| *existing = existing.merge(role))
+        .or_insert(role);
+}
+
+// `name` がスコープ内のCTEであれば物理テーブルとしては記録しない
+fn insert_table_ref(ctx: &mut Extraction, visible_ctes: &BTreeSet<String>, name: String, role: TableRole) {
+    if visible_ctes.contains(&name) {
+        return;
+    }
+    insert_role(&mut ctx.tables, name, role);
+}
+
+fn collect_tables_from_query(query: &Query, visible_ctes: &BTreeSet<String>, ctx: &mut Extraction) {
+    // WITH (CTE) はこのクエリの直下でのみ可視。シャドーイングに備えて親スコープに追加する形でコピーする
+    let mut scope = visible_ctes.clone();
     if let Some(with) = &query.with {
         for cte in &with.cte_tables {
-            collect_tables_from_query(&cte.query, out);
+            let name = cte.alias.name.value.clone();
+            scope.insert(name.clone());
+            ctx.ctes.insert(name);
+        }
+        // 再帰CTEは自分自身の名前を参照できるよう、本体もこのスコープで辿る
+        for cte in &with.cte_tables {
+            collect_tables_from_query(&cte.query, &scope, ctx);
         }
     }
 
+    // 探索用のキュー（FROM句・JOIN・サブクエリ・セット演算など）
+    let mut queue: VecDeque<SetExpr> = VecDeque::new();
+    queue.push_back((*query.body).clone());
+
     while let Some(expr) = queue.pop_front() {
         match expr {
             SetExpr::Select(select) => {
-                collect_tables_from_select(&select, out);
+                collect_tables_from_select(&select, &scope, ctx);
             }
             SetExpr::Query(q) => {
-                collect_tables_from_query(&q, out);
+                collect_tables_from_query(&q, &scope, ctx);
             }
             SetExpr::SetOperation { left, right, .. } => {
                 queue.push_back(*left);
                 queue.push_back(*right);
             }
-            SetExpr::Values(_) => {}
+            SetExpr::Values(values) => {
+                for row in &values.rows {
+                    for e in row {
+                        collect_tables_from_expr(e, &scope, ctx);
+                    }
+                }
+            }
             _ => {}
         }
     }
@@ -73,31 +190,31 @@ fn object_name_to_string(name: &ObjectName) -> String {
 
 // 補助: なし（シンプルに副作用で集計）
 
-fn from_table_with_joins_single(twj: &TableWithJoins, out: &mut BTreeSet<String>) {
+fn from_table_with_joins_single(twj: &TableWithJoins, visible_ctes: &BTreeSet<String>, ctx: &mut Extraction) {
     match &twj.relation {
         TableFactor::Table { name, .. } => {
-            out.insert(object_name_to_string(name));
+            insert_table_ref(ctx, visible_ctes, object_name_to_string(name), TableRole::Read);
         }
         TableFactor::Derived { subquery, .. } => {
-            collect_tables_from_query(subquery, out);
+            collect_tables_from_query(subquery, visible_ctes, ctx);
         }
         TableFactor::TableFunction { .. } => {}
         TableFactor::NestedJoin { table_with_joins: nested, .. } => {
             // ( ... ) の中身
             match &nested.relation {
                 TableFactor::Table { name, .. } => {
-                    out.insert(object_name_to_string(&name));
+                    insert_table_ref(ctx, visible_ctes, object_name_to_string(name), TableRole::Read);
                 }
                 TableFactor::Derived { subquery, .. } => {
-                    collect_tables_from_query(&subquery, out);
+                    collect_tables_from_query(subquery, visible_ctes, ctx);
                 }
                 _ => {}
             }
             for j in &nested.joins {
                 if let TableFactor::Table { name, .. } = &j.relation {
-                    out.insert(object_name_to_string(&name));
+                    insert_table_ref(ctx, visible_ctes, object_name_to_string(name), TableRole::Read);
                 } else if let TableFactor::Derived { subquery, .. } = &j.relation {
-                    collect_tables_from_query(&subquery, out);
+                    collect_tables_from_query(subquery, visible_ctes, ctx);
                 }
             }
         }
@@ -108,139 +225,603 @@ fn from_table_with_joins_single(twj: &TableWithJoins, out: &mut BTreeSet<String>
     for j in &twj.joins {
         match &j.relation {
             TableFactor::Table { name, .. } => {
-                out.insert(object_name_to_string(&name));
+                insert_table_ref(ctx, visible_ctes, object_name_to_string(name), TableRole::Read);
             }
             TableFactor::Derived { subquery, .. } => {
-                collect_tables_from_query(&subquery, out);
+                collect_tables_from_query(subquery, visible_ctes, ctx);
             }
             _ => {}
         }
     }
 }
 
-fn from_table_with_joins(select: &Select, out: &mut BTreeSet<String>) {
+fn from_table_with_joins(select: &Select, visible_ctes: &BTreeSet<String>, ctx: &mut Extraction) {
     for twj in &select.from {
-        from_table_with_joins_single(twj, out);
+        from_table_with_joins_single(twj, visible_ctes, ctx);
     }
 }
 
-fn collect_tables_from_select(select: &Select, out: &mut BTreeSet<String>) {
-    from_table_with_joins(select, out);
+fn collect_tables_from_select(select: &Select, visible_ctes: &BTreeSet<String>, ctx: &mut Extraction) {
+    from_table_with_joins(select, visible_ctes, ctx);
 
     // SELECT リスト内の式に含まれるサブクエリ
     for item in &select.projection {
         match item {
-            SelectItem::UnnamedExpr(e) => collect_tables_from_expr(e, out),
-            SelectItem::ExprWithAlias { expr, .. } => collect_tables_from_expr(expr, out),
+            SelectItem::UnnamedExpr(e) => collect_tables_from_expr(e, visible_ctes, ctx),
+            SelectItem::ExprWithAlias { expr, .. } => collect_tables_from_expr(expr, visible_ctes, ctx),
             _ => {}
         }
     }
 
     // WHERE / HAVING 内のサブクエリ
     if let Some(selection) = &select.selection {
-        collect_tables_from_expr(selection, out);
+        collect_tables_from_expr(selection, visible_ctes, ctx);
     }
     if let Some(having) = &select.having {
-        collect_tables_from_expr(having, out);
+        collect_tables_from_expr(having, visible_ctes, ctx);
     }
 
     // GROUP BY は一旦スキップ（テーブル抽出には不要）
 }
 
-fn collect_tables_from_expr(expr: &Expr, out: &mut BTreeSet<String>) {
+fn collect_tables_from_function_arg_expr(arg: &FunctionArgExpr, visible_ctes: &BTreeSet<String>, ctx: &mut Extraction) {
+    match arg {
+        FunctionArgExpr::Expr(e) => collect_tables_from_expr(e, visible_ctes, ctx),
+        FunctionArgExpr::QualifiedWildcard(_) | FunctionArgExpr::Wildcard => {}
+    }
+}
+
+fn collect_tables_from_function(func: &Function, visible_ctes: &BTreeSet<String>, ctx: &mut Extraction) {
+    for arg in &func.args {
+        match arg {
+            FunctionArg::Named { arg, .. } => collect_tables_from_function_arg_expr(arg, visible_ctes, ctx),
+            FunctionArg::Unnamed(arg) => collect_tables_from_function_arg_expr(arg, visible_ctes, ctx),
+        }
+    }
+    if let Some(WindowType::WindowSpec(over)) = &func.over {
+        collect_tables_from_window_spec(over, visible_ctes, ctx);
+    }
+    for o in &func.order_by {
+        collect_tables_from_expr(&o.expr, visible_ctes, ctx);
+    }
+}
+
+fn collect_tables_from_window_spec(spec: &WindowSpec, visible_ctes: &BTreeSet<String>, ctx: &mut Extraction) {
+    for e in &spec.partition_by {
+        collect_tables_from_expr(e, visible_ctes, ctx);
+    }
+    for o in &spec.order_by {
+        collect_tables_from_expr(&o.expr, visible_ctes, ctx);
+    }
+}
+
+// `Expr` の全バリアントを網羅的に辿り、どこに埋め込まれていてもサブクエリを拾う
+fn collect_tables_from_expr(expr: &Expr, visible_ctes: &BTreeSet<String>, ctx: &mut Extraction) {
     match expr {
-        Expr::InSubquery { subquery, .. }
-        | Expr::Exists { subquery, .. }
-        | Expr::Subquery(subquery) => {
-            collect_tables_from_query(subquery, out);
+        Expr::InSubquery { expr, subquery, .. } => {
+            collect_tables_from_expr(expr, visible_ctes, ctx);
+            collect_tables_from_query(subquery, visible_ctes, ctx);
+        }
+        Expr::Exists { subquery, .. } => collect_tables_from_query(subquery, visible_ctes, ctx),
+        Expr::Subquery(subquery) | Expr::ArraySubquery(subquery) => {
+            collect_tables_from_query(subquery, visible_ctes, ctx);
         }
         Expr::BinaryOp { left, right, .. } => {
-            collect_tables_from_expr(left, out);
-            collect_tables_from_expr(right, out);
+            collect_tables_from_expr(left, visible_ctes, ctx);
+            collect_tables_from_expr(right, visible_ctes, ctx);
         }
-        Expr::UnaryOp { expr, .. } => collect_tables_from_expr(expr, out),
-        Expr::Cast { expr, .. } => collect_tables_from_expr(expr, out),
-        Expr::Extract { expr, .. } => collect_tables_from_expr(expr, out),
-        Expr::Nested(e) => collect_tables_from_expr(e, out),
+        Expr::UnaryOp { expr, .. } => collect_tables_from_expr(expr, visible_ctes, ctx),
+        Expr::Cast { expr, .. } | Expr::TryCast { expr, .. } | Expr::SafeCast { expr, .. } => {
+            collect_tables_from_expr(expr, visible_ctes, ctx)
+        }
+        Expr::Extract { expr, .. } => collect_tables_from_expr(expr, visible_ctes, ctx),
+        Expr::Nested(e) => collect_tables_from_expr(e, visible_ctes, ctx),
         Expr::Case { operand, conditions, results, else_result } => {
-            if let Some(op) = operand { collect_tables_from_expr(op, out); }
-            for c in conditions { collect_tables_from_expr(c, out); }
-            for r in results { collect_tables_from_expr(r, out); }
-            if let Some(er) = else_result { collect_tables_from_expr(er, out); }
+            if let Some(op) = operand { collect_tables_from_expr(op, visible_ctes, ctx); }
+            for c in conditions { collect_tables_from_expr(c, visible_ctes, ctx); }
+            for r in results { collect_tables_from_expr(r, visible_ctes, ctx); }
+            if let Some(er) = else_result { collect_tables_from_expr(er, visible_ctes, ctx); }
         }
-        // 関数内の式解析は一旦スキップ（テーブル抽出には不要なことが多い）
-        Expr::Function(_) => {}
+        Expr::Function(func) => collect_tables_from_function(func, visible_ctes, ctx),
         Expr::Between { expr, low, high, .. } => {
-            collect_tables_from_expr(expr, out);
-            collect_tables_from_expr(low, out);
-            collect_tables_from_expr(high, out);
+            collect_tables_from_expr(expr, visible_ctes, ctx);
+            collect_tables_from_expr(low, visible_ctes, ctx);
+            collect_tables_from_expr(high, visible_ctes, ctx);
+        }
+        Expr::Tuple(exprs) | Expr::Array(Array { elem: exprs, .. }) => {
+            for e in exprs { collect_tables_from_expr(e, visible_ctes, ctx); }
+        }
+        Expr::InList { expr, list, .. } => {
+            collect_tables_from_expr(expr, visible_ctes, ctx);
+            for e in list { collect_tables_from_expr(e, visible_ctes, ctx); }
+        }
+        Expr::InUnnest { expr, array_expr, .. } => {
+            collect_tables_from_expr(expr, visible_ctes, ctx);
+            collect_tables_from_expr(array_expr, visible_ctes, ctx);
+        }
+        Expr::Like { expr, pattern, .. }
+        | Expr::ILike { expr, pattern, .. }
+        | Expr::SimilarTo { expr, pattern, .. } => {
+            collect_tables_from_expr(expr, visible_ctes, ctx);
+            collect_tables_from_expr(pattern, visible_ctes, ctx);
+        }
+        Expr::AnyOp(e) | Expr::AllOp(e) => collect_tables_from_expr(e, visible_ctes, ctx),
+        Expr::IsNull(e)
+        | Expr::IsNotNull(e)
+        | Expr::IsTrue(e)
+        | Expr::IsNotTrue(e)
+        | Expr::IsFalse(e)
+        | Expr::IsNotFalse(e)
+        | Expr::IsUnknown(e)
+        | Expr::IsNotUnknown(e) => collect_tables_from_expr(e, visible_ctes, ctx),
+        Expr::IsDistinctFrom(left, right) | Expr::IsNotDistinctFrom(left, right) => {
+            collect_tables_from_expr(left, visible_ctes, ctx);
+            collect_tables_from_expr(right, visible_ctes, ctx);
         }
-        Expr::Tuple(exprs) => { for e in exprs { collect_tables_from_expr(e, out); } }
+        Expr::Collate { expr, .. } => collect_tables_from_expr(expr, visible_ctes, ctx),
+        Expr::MapAccess { column, keys } => {
+            collect_tables_from_expr(column, visible_ctes, ctx);
+            for k in keys { collect_tables_from_expr(k, visible_ctes, ctx); }
+        }
+        Expr::ArrayIndex { obj, indexes } => {
+            collect_tables_from_expr(obj, visible_ctes, ctx);
+            for i in indexes { collect_tables_from_expr(i, visible_ctes, ctx); }
+        }
+        Expr::Position { expr, r#in } => {
+            collect_tables_from_expr(expr, visible_ctes, ctx);
+            collect_tables_from_expr(r#in, visible_ctes, ctx);
+        }
+        Expr::Substring { expr, substring_from, substring_for, .. } => {
+            collect_tables_from_expr(expr, visible_ctes, ctx);
+            if let Some(f) = substring_from { collect_tables_from_expr(f, visible_ctes, ctx); }
+            if let Some(f) = substring_for { collect_tables_from_expr(f, visible_ctes, ctx); }
+        }
+        Expr::Trim { expr, trim_what, .. } => {
+            collect_tables_from_expr(expr, visible_ctes, ctx);
+            if let Some(w) = trim_what { collect_tables_from_expr(w, visible_ctes, ctx); }
+        }
+        Expr::Overlay { expr, overlay_what, overlay_from, overlay_for } => {
+            collect_tables_from_expr(expr, visible_ctes, ctx);
+            collect_tables_from_expr(overlay_what, visible_ctes, ctx);
+            collect_tables_from_expr(overlay_from, visible_ctes, ctx);
+            if let Some(f) = overlay_for { collect_tables_from_expr(f, visible_ctes, ctx); }
+        }
+        Expr::AtTimeZone { timestamp, .. } => collect_tables_from_expr(timestamp, visible_ctes, ctx),
+        Expr::Ceil { expr, .. } | Expr::Floor { expr, .. } | Expr::CompositeAccess { expr, .. } => {
+            collect_tables_from_expr(expr, visible_ctes, ctx)
+        }
+        Expr::JsonAccess { left, right, .. } => {
+            collect_tables_from_expr(left, visible_ctes, ctx);
+            collect_tables_from_expr(right, visible_ctes, ctx);
+        }
+        Expr::AggregateExpressionWithFilter { expr, filter } => {
+            collect_tables_from_expr(expr, visible_ctes, ctx);
+            collect_tables_from_expr(filter, visible_ctes, ctx);
+        }
+        Expr::ArrayAgg(ArrayAgg { expr, .. }) | Expr::ListAgg(ListAgg { expr, .. }) => {
+            collect_tables_from_expr(expr, visible_ctes, ctx)
+        }
+        Expr::Interval(Interval { value, .. }) => collect_tables_from_expr(value, visible_ctes, ctx),
+        Expr::GroupingSets(sets) | Expr::Cube(sets) | Expr::Rollup(sets) => {
+            for set in sets {
+                for e in set { collect_tables_from_expr(e, visible_ctes, ctx); }
+            }
+        }
+        // 識別子・リテラルなど、これ以上辿るものを持たないノード
+        Expr::Identifier(_)
+        | Expr::CompoundIdentifier(_)
+        | Expr::Value(_)
+        | Expr::TypedString { .. } => {}
         _ => {}
     }
 }
 
-fn extract_tables(statements: &[Statement]) -> BTreeSet<String> {
-    let mut tables = BTreeSet::new();
+// UPDATE の対象テーブル（直接 JOIN された相手は読み取り側）
+fn collect_update_target(table: &TableWithJoins, visible_ctes: &BTreeSet<String>, ctx: &mut Extraction) {
+    match &table.relation {
+        TableFactor::Table { name, .. } => {
+            insert_table_ref(ctx, visible_ctes, object_name_to_string(name), TableRole::Write);
+        }
+        TableFactor::Derived { subquery, .. } => {
+            collect_tables_from_query(subquery, visible_ctes, ctx);
+        }
+        _ => {}
+    }
+    for j in &table.joins {
+        match &j.relation {
+            TableFactor::Table { name, .. } => {
+                insert_table_ref(ctx, visible_ctes, object_name_to_string(name), TableRole::Read);
+            }
+            TableFactor::Derived { subquery, .. } => {
+                collect_tables_from_query(subquery, visible_ctes, ctx);
+            }
+            _ => {}
+        }
+    }
+}
+
+fn collect_merge_side(table: &TableFactor, role: TableRole, visible_ctes: &BTreeSet<String>, ctx: &mut Extraction) {
+    match table {
+        TableFactor::Table { name, .. } => {
+            insert_table_ref(ctx, visible_ctes, object_name_to_string(name), role);
+        }
+        TableFactor::Derived { subquery, .. } => {
+            collect_tables_from_query(subquery, visible_ctes, ctx);
+        }
+        _ => {}
+    }
+}
+
+// target が読んでいる物理テーブル群を、個別のスコープで集計してから
+// ctx にマージし、target ← source のリネージ辺を記録する
+fn merge_as_lineage_source(ctx: &mut Extraction, target: &str, query: &Query) {
+    let mut sources = Extraction::default();
+    collect_tables_from_query(query, &BTreeSet::new(), &mut sources);
+    for (name, role) in &sources.tables {
+        insert_role(&mut ctx.tables, name.clone(), *role);
+        ctx.edges.push((target.to_string(), name.clone()));
+    }
+    ctx.ctes.extend(sources.ctes);
+}
+
+fn extract_tables(statements: &[Statement]) -> Extraction {
+    let mut ctx = Extraction::default();
+    // 文のトップレベルではまだどのCTEも可視ではない
+    let top_scope: BTreeSet<String> = BTreeSet::new();
     for stmt in statements {
         match stmt {
-            Statement::Query(q) => collect_tables_from_query(q, &mut tables),
-            
-            // CREATE VIEW文の対応
-            Statement::CreateView { query, .. } => {
-                collect_tables_from_query(query, &mut tables);
+            Statement::Query(q) => collect_tables_from_query(q, &top_scope, &mut ctx),
+
+            // CREATE VIEW文の対応: ビュー名 ← SELECT元のテーブル
+            Statement::CreateView { name, query, .. } => {
+                merge_as_lineage_source(&mut ctx, &object_name_to_string(name), query);
+            }
+
+            // CREATE TABLE AS SELECT文の対応: テーブル名 ← SELECT元のテーブル
+            Statement::CreateTable { name, query: Some(q), .. } => {
+                merge_as_lineage_source(&mut ctx, &object_name_to_string(name), q);
+            }
+
+            // INSERT INTO t ... SELECT ... : t は書き込み、SELECT側は読み取り
+            Statement::Insert { table_name, source, .. } => {
+                let target = object_name_to_string(table_name);
+                insert_role(&mut ctx.tables, target.clone(), TableRole::Write);
+                merge_as_lineage_source(&mut ctx, &target, source);
             }
-            
-            // CREATE TABLE AS SELECT文の対応
-            Statement::CreateTable { query, .. } => {
-                if let Some(q) = query {
-                    collect_tables_from_query(q, &mut tables);
+
+            // UPDATE t SET ... FROM ... WHERE ... : t は書き込み、FROM/WHEREは読み取り
+            // （UPDATE ... FROM は一部の方言のみサポートするため from は任意）
+            Statement::Update { table, assignments, from, selection, .. } => {
+                collect_update_target(table, &top_scope, &mut ctx);
+                for assignment in assignments {
+                    collect_tables_from_expr(&assignment.value, &top_scope, &mut ctx);
+                }
+                if let Some(from) = from {
+                    from_table_with_joins_single(from, &top_scope, &mut ctx);
+                }
+                if let Some(selection) = selection {
+                    collect_tables_from_expr(selection, &top_scope, &mut ctx);
                 }
             }
-            
-            // その他のDML/DDL文のサポートは将来追加予定
-            // INSERT, UPDATE, DELETE文等も今後対応可能
+
+            // DELETE FROM t USING ... WHERE ... : t は書き込み、USING/WHEREは読み取り
+            // MySQLの複数テーブル削除（DELETE t1, t2 FROM ...）では `tables` が削除対象を明示するので、
+            // それが空でなければFROM上の位置ではなく`tables`を優先して役割を決める
+            Statement::Delete { tables, from, using, selection, .. } => {
+                let delete_targets: BTreeSet<String> = tables.iter().map(object_name_to_string).collect();
+                for twj in from {
+                    if let TableFactor::Table { name, .. } = &twj.relation {
+                        let name = object_name_to_string(name);
+                        let role = if delete_targets.is_empty() || delete_targets.contains(&name) {
+                            TableRole::Write
+                        } else {
+                            TableRole::Read
+                        };
+                        insert_role(&mut ctx.tables, name, role);
+                    } else if let TableFactor::Derived { subquery, .. } = &twj.relation {
+                        collect_tables_from_query(subquery, &top_scope, &mut ctx);
+                    }
+                    for j in &twj.joins {
+                        if let TableFactor::Table { name, .. } = &j.relation {
+                            let name = object_name_to_string(name);
+                            let role = if delete_targets.contains(&name) { TableRole::Write } else { TableRole::Read };
+                            insert_role(&mut ctx.tables, name, role);
+                        } else if let TableFactor::Derived { subquery, .. } = &j.relation {
+                            collect_tables_from_query(subquery, &top_scope, &mut ctx);
+                        }
+                    }
+                }
+                if let Some(using) = using {
+                    for twj in using {
+                        from_table_with_joins_single(twj, &top_scope, &mut ctx);
+                    }
+                }
+                if let Some(selection) = selection {
+                    collect_tables_from_expr(selection, &top_scope, &mut ctx);
+                }
+            }
+
+            // MERGE INTO target USING source ON ... : target は書き込み、source は読み取り
+            Statement::Merge { table, source, on, clauses, .. } => {
+                collect_merge_side(table, TableRole::Write, &top_scope, &mut ctx);
+                collect_merge_side(source, TableRole::Read, &top_scope, &mut ctx);
+                collect_tables_from_expr(on, &top_scope, &mut ctx);
+                for clause in clauses {
+                    match clause {
+                        MergeClause::MatchedUpdate { predicate, assignments } => {
+                            if let Some(predicate) = predicate {
+                                collect_tables_from_expr(predicate, &top_scope, &mut ctx);
+                            }
+                            for assignment in assignments {
+                                collect_tables_from_expr(&assignment.value, &top_scope, &mut ctx);
+                            }
+                        }
+                        MergeClause::MatchedDelete(predicate) => {
+                            if let Some(predicate) = predicate {
+                                collect_tables_from_expr(predicate, &top_scope, &mut ctx);
+                            }
+                        }
+                        MergeClause::NotMatched { predicate, .. } => {
+                            if let Some(predicate) = predicate {
+                                collect_tables_from_expr(predicate, &top_scope, &mut ctx);
+                            }
+                        }
+                    }
+                }
+            }
+
+            // その他のDDL文のサポートは将来追加予定
             _ => {
                 // 他のSQL文タイプは今のところスキップ
                 // 必要に応じて段階的に追加していく
             }
         }
     }
-    tables
+    ctx
+}
+
+// `--columns` モード: SELECTが参照する列を、可能な限り table.column に解決する
+#[derive(Debug, Default)]
+struct ColumnExtraction {
+    resolved: BTreeSet<(String, String)>,
+    // 修飾がない、またはエイリアスが解決できなかった列
+    ambiguous: BTreeSet<String>,
+}
+
+// FROM/JOIN に現れる別名（エイリアス）からテーブル名への対応表を作る
+fn build_alias_map(select: &Select) -> BTreeMap<String, String> {
+    let mut map = BTreeMap::new();
+    for twj in &select.from {
+        add_alias_binding(&twj.relation, &mut map);
+        for j in &twj.joins {
+            add_alias_binding(&j.relation, &mut map);
+        }
+    }
+    map
+}
+
+fn add_alias_binding(relation: &TableFactor, map: &mut BTreeMap<String, String>) {
+    match relation {
+        TableFactor::Table { name, alias, .. } => {
+            let table = object_name_to_string(name);
+            let key = alias.as_ref().map(|a| a.name.value.clone()).unwrap_or_else(|| table.clone());
+            map.insert(key, table);
+        }
+        TableFactor::Derived { alias: Some(alias), .. } => {
+            // 派生テーブルは物理テーブルに解決できないため、エイリアス自身を出所として扱う
+            map.insert(alias.name.value.clone(), alias.name.value.clone());
+        }
+        _ => {}
+    }
+}
+
+fn attribute_column(idents: &[Ident], alias_map: &BTreeMap<String, String>, out: &mut ColumnExtraction) {
+    match idents.len() {
+        0 => {}
+        1 => {
+            let column = idents[0].value.clone();
+            // FROM/JOINに候補テーブルが1つしかなければ、無修飾でも一意に解決できる
+            if alias_map.len() == 1 {
+                let table = alias_map.values().next().expect("len == 1");
+                out.resolved.insert((table.clone(), column));
+            } else {
+                out.ambiguous.insert(column);
+            }
+        }
+        n => {
+            let qualifier = &idents[n - 2].value;
+            let column = idents[n - 1].value.clone();
+            match alias_map.get(qualifier) {
+                Some(table) => {
+                    out.resolved.insert((table.clone(), column));
+                }
+                None => {
+                    out.ambiguous.insert(format!("{}.{}", qualifier, column));
+                }
+            }
+        }
+    }
+}
+
+// 式の中の列参照を辿る。ネストしたサブクエリは別スコープなので立ち入らない
+fn collect_columns_from_expr(expr: &Expr, alias_map: &BTreeMap<String, String>, out: &mut ColumnExtraction) {
+    match expr {
+        Expr::Identifier(ident) => attribute_column(std::slice::from_ref(ident), alias_map, out),
+        Expr::CompoundIdentifier(idents) => attribute_column(idents, alias_map, out),
+        Expr::BinaryOp { left, right, .. } => {
+            collect_columns_from_expr(left, alias_map, out);
+            collect_columns_from_expr(right, alias_map, out);
+        }
+        Expr::UnaryOp { expr, .. }
+        | Expr::Cast { expr, .. }
+        | Expr::TryCast { expr, .. }
+        | Expr::SafeCast { expr, .. }
+        | Expr::Nested(expr)
+        | Expr::Collate { expr, .. }
+        | Expr::Extract { expr, .. } => collect_columns_from_expr(expr, alias_map, out),
+        Expr::Between { expr, low, high, .. } => {
+            collect_columns_from_expr(expr, alias_map, out);
+            collect_columns_from_expr(low, alias_map, out);
+            collect_columns_from_expr(high, alias_map, out);
+        }
+        Expr::InList { expr, list, .. } => {
+            collect_columns_from_expr(expr, alias_map, out);
+            for e in list { collect_columns_from_expr(e, alias_map, out); }
+        }
+        // サブクエリ自体には立ち入らないが、比較対象の外側の式はこのスコープの列なので辿る
+        Expr::InSubquery { expr, .. } => collect_columns_from_expr(expr, alias_map, out),
+        Expr::Like { expr, pattern, .. } | Expr::ILike { expr, pattern, .. } | Expr::SimilarTo { expr, pattern, .. } => {
+            collect_columns_from_expr(expr, alias_map, out);
+            collect_columns_from_expr(pattern, alias_map, out);
+        }
+        Expr::Case { operand, conditions, results, else_result } => {
+            if let Some(op) = operand { collect_columns_from_expr(op, alias_map, out); }
+            for c in conditions { collect_columns_from_expr(c, alias_map, out); }
+            for r in results { collect_columns_from_expr(r, alias_map, out); }
+            if let Some(er) = else_result { collect_columns_from_expr(er, alias_map, out); }
+        }
+        Expr::Function(func) => {
+            for arg in &func.args {
+                let arg_expr = match arg {
+                    FunctionArg::Named { arg, .. } | FunctionArg::Unnamed(arg) => arg,
+                };
+                if let FunctionArgExpr::Expr(e) = arg_expr {
+                    collect_columns_from_expr(e, alias_map, out);
+                }
+            }
+        }
+        Expr::Tuple(exprs) => {
+            for e in exprs { collect_columns_from_expr(e, alias_map, out); }
+        }
+        _ => {}
+    }
+}
+
+fn collect_columns_from_select(select: &Select, out: &mut ColumnExtraction) {
+    let alias_map = build_alias_map(select);
+
+    for item in &select.projection {
+        match item {
+            SelectItem::UnnamedExpr(e) => collect_columns_from_expr(e, &alias_map, out),
+            SelectItem::ExprWithAlias { expr, .. } => collect_columns_from_expr(expr, &alias_map, out),
+            _ => {}
+        }
+    }
+    if let Some(selection) = &select.selection {
+        collect_columns_from_expr(selection, &alias_map, out);
+    }
+    if let Some(having) = &select.having {
+        collect_columns_from_expr(having, &alias_map, out);
+    }
+    for e in &select.group_by {
+        collect_columns_from_expr(e, &alias_map, out);
+    }
+}
+
+fn collect_columns_from_set_expr(expr: &SetExpr, out: &mut ColumnExtraction) {
+    match expr {
+        SetExpr::Select(select) => collect_columns_from_select(select, out),
+        SetExpr::Query(q) => collect_columns_from_query(q, out),
+        SetExpr::SetOperation { left, right, .. } => {
+            collect_columns_from_set_expr(left, out);
+            collect_columns_from_set_expr(right, out);
+        }
+        _ => {}
+    }
+}
+
+fn collect_columns_from_query(query: &Query, out: &mut ColumnExtraction) {
+    if let Some(with) = &query.with {
+        for cte in &with.cte_tables {
+            collect_columns_from_query(&cte.query, out);
+        }
+    }
+    // ORDER BY はクエリ全体にかかるため、トップレベルがSELECTであればそのエイリアス表で解決する
+    let alias_map = match &*query.body {
+        SetExpr::Select(select) => build_alias_map(select),
+        _ => BTreeMap::new(),
+    };
+    collect_columns_from_set_expr(&query.body, out);
+    for o in &query.order_by {
+        collect_columns_from_expr(&o.expr, &alias_map, out);
+    }
+}
+
+fn extract_columns(statements: &[Statement]) -> ColumnExtraction {
+    let mut out = ColumnExtraction::default();
+    for stmt in statements {
+        if let Statement::Query(q) = stmt {
+            collect_columns_from_query(q, &mut out);
+        }
+    }
+    out
+}
+
+#[derive(Debug, Clone, Copy)]
+enum OutputFormat {
+    Text,
+    Json,
+    Dot,
+}
+
+// 固定の方言で解析するか、`--dialect auto` で全方言を試すか
+#[derive(Debug, Clone, Copy)]
+enum DialectMode {
+    Fixed(DialectKind),
+    Auto,
 }
 
 #[derive(Debug)]
 struct CliArgs {
-    dialect: DialectKind,
+    dialect: DialectMode,
+    format: OutputFormat,
+    // --probe: --dialect の指定にかかわらず、全方言についてパース可否の一覧を表示する
+    probe: bool,
+    // --columns: テーブルではなく、参照されている table.column を列挙する
+    columns: bool,
     sql: String,
+    // --file で読んだ場合はそのパス、--sql の場合は "<sql>"（診断メッセージの見出しに使う）
+    source_label: String,
 }
 
 fn parse_args() -> Result<CliArgs, String> {
     let mut args = std::env::args().skip(1);
-    let mut dialect = DialectKind::Generic;
+    let mut dialect = DialectMode::Fixed(DialectKind::Generic);
+    let mut format = OutputFormat::Text;
+    let mut probe = false;
+    let mut columns = false;
     let mut sql: Option<String> = None;
+    let mut source_label = String::from("<sql>");
 
     while let Some(arg) = args.next() {
         match arg.as_str() {
             "--dialect" => {
                 let v = args.next().ok_or("--dialect の値が必要です")?;
-                dialect = match v.to_lowercase().as_str() {
-                    "generic" => DialectKind::Generic,
-                    "postgres" | "postgresql" => DialectKind::Postgres,
-                    "mysql" => DialectKind::MySql,
-                    "mssql" => DialectKind::MsSql,
-                    "snowflake" => DialectKind::Snowflake,
-                    "bigquery" => DialectKind::BigQuery,
-                    "sqlite" => DialectKind::SQLite,
-                    "hive" => DialectKind::Hive,
-                    "ansi" => DialectKind::Ansi,
-                    "redshift" => DialectKind::Redshift,
-                    _ => return Err(format!("未知のdialect: {}", v)),
+                dialect = if v.to_lowercase() == "auto" {
+                    DialectMode::Auto
+                } else {
+                    DialectMode::Fixed(dialect_from_str(&v)?)
+                };
+            }
+            "--probe" => {
+                probe = true;
+            }
+            "--columns" => {
+                columns = true;
+            }
+            "--format" => {
+                let v = args.next().ok_or("--format の値が必要です")?;
+                format = match v.to_lowercase().as_str() {
+                    "text" => OutputFormat::Text,
+                    "json" => OutputFormat::Json,
+                    "dot" => OutputFormat::Dot,
+                    _ => return Err(format!("未知のformat: {}", v)),
                 };
             }
             "--file" => {
                 let path = PathBuf::from(args.next().ok_or("--file の値が必要です")?);
                 sql = Some(fs::read_to_string(&path).map_err(|e| format!("ファイル読み込み失敗: {}", e))?);
+                source_label = path.display().to_string();
             }
             "--sql" => {
                 sql = Some(args.next().ok_or("--sql の値が必要です")?);
@@ -250,7 +831,173 @@ fn parse_args() -> Result<CliArgs, String> {
     }
 
     let sql = sql.ok_or("--file もしくは --sql でSQLを与えてください")?;
-    Ok(CliArgs { dialect, sql })
+    Ok(CliArgs { dialect, format, probe, columns, sql, source_label })
+}
+
+// 全方言を順に試し、最初に成功したものを返す
+fn parse_sql_auto(sql: &str) -> Result<(DialectKind, Vec<Statement>), String> {
+    let mut last_err = String::new();
+    for &dialect in &ALL_DIALECTS {
+        match parse_sql_with_dialect(sql, dialect) {
+            Ok(stmts) => return Ok((dialect, stmts)),
+            Err(e) => last_err = e,
+        }
+    }
+    Err(format!("どの方言でもパースできませんでした（最後のエラー: {}）", last_err))
+}
+
+// 全方言について、パースできるかどうかの一覧を表示する
+fn print_dialect_probe(sql: &str) {
+    for &dialect in &ALL_DIALECTS {
+        match parse_sql_with_dialect(sql, dialect) {
+            Ok(_) => println!("{}\tOK", dialect_name(dialect)),
+            Err(e) => println!("{}\tNG\t{}", dialect_name(dialect), e),
+        }
+    }
+}
+
+// sqlparserのエラーメッセージ末尾に含まれる "Line: N, Column M" を抜き出す
+// （sqlparser自体の表記に合わせ、"Column" の後にコロンは付かない）
+fn extract_line_col(err: &str) -> Option<(usize, usize)> {
+    let line_idx = err.find("Line: ")?;
+    let after_line = &err[line_idx + "Line: ".len()..];
+    let line_digits: String = after_line.chars().take_while(|c| c.is_ascii_digit()).collect();
+    let line: usize = line_digits.parse().ok()?;
+
+    let col_idx = after_line.find("Column ")?;
+    let after_col = &after_line[col_idx + "Column ".len()..];
+    let col_digits: String = after_col.chars().take_while(|c| c.is_ascii_digit()).collect();
+    let col: usize = col_digits.parse().ok()?;
+
+    Some((line, col))
+}
+
+// sqlparserのTokenizerErrorはメッセージに位置を埋め込むが、ParserError（構文上の誤り、
+// 例えば式の欠落や余分なキーワード）はそうしない。その場合は自前で再トークナイズし、
+// 最後まで正常に読めたトークンの位置をエラー発生箇所の近似値として使う
+fn last_token_location(sql: &str, dialect: DialectKind) -> Option<(usize, usize)> {
+    let dialect_impl = build_dialect_impl(dialect);
+    let tokens = Tokenizer::new(&*dialect_impl, sql).tokenize_with_location().ok()?;
+    let last = tokens.last()?;
+    Some((last.location.line as usize, last.location.column as usize))
+}
+
+fn locate_parse_error(sql: &str, dialect: DialectKind, err: &str) -> Option<(usize, usize)> {
+    extract_line_col(err).or_else(|| last_token_location(sql, dialect))
+}
+
+// コンパイラ診断のように、該当行とキャレットを添えてパースエラーを整形する
+fn format_parse_error(source_label: &str, sql: &str, dialect: DialectKind, err: &str) -> String {
+    let Some((line, col)) = locate_parse_error(sql, dialect, err) else {
+        return format!("{}: SQLパースに失敗しました: {}", source_label, err);
+    };
+    let lines: Vec<&str> = sql.lines().collect();
+    let Some(src_line) = line.checked_sub(1).and_then(|i| lines.get(i)) else {
+        return format!("{}:{}:{}: SQLパースに失敗しました: {}", source_label, line, col, err);
+    };
+
+    let mut caret = String::new();
+    for _ in 1..col {
+        caret.push(' ');
+    }
+    caret.push('^');
+
+    format!(
+        "{}:{}:{}: SQLパースに失敗しました: {}\n{}\n{}",
+        source_label, line, col, err, src_line, caret
+    )
+}
+
+fn json_escape(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+fn print_text(extraction: &Extraction) {
+    for (t, role) in &extraction.tables {
+        println!("{}\t{}", t, role);
+    }
+    if !extraction.ctes.is_empty() {
+        println!("--- CTEs ---");
+        for name in &extraction.ctes {
+            println!("{}", name);
+        }
+    }
+}
+
+fn build_json(extraction: &Extraction) -> String {
+    let mut out = String::from("{\n");
+
+    out.push_str("  \"tables\": [\n");
+    let entries: Vec<String> = extraction
+        .tables
+        .iter()
+        .map(|(name, role)| format!("    {{ \"name\": \"{}\", \"role\": \"{}\" }}", json_escape(name), role))
+        .collect();
+    out.push_str(&entries.join(",\n"));
+    out.push_str("\n  ],\n");
+
+    out.push_str("  \"ctes\": [\n");
+    let ctes: Vec<String> = extraction.ctes.iter().map(|c| format!("    \"{}\"", json_escape(c))).collect();
+    out.push_str(&ctes.join(",\n"));
+    out.push_str("\n  ],\n");
+
+    out.push_str("  \"edges\": [\n");
+    let edges: Vec<String> = extraction
+        .edges
+        .iter()
+        .map(|(target, source)| {
+            format!(
+                "    {{ \"target\": \"{}\", \"source\": \"{}\" }}",
+                json_escape(target),
+                json_escape(source)
+            )
+        })
+        .collect();
+    out.push_str(&edges.join(",\n"));
+    out.push_str("\n  ]\n}");
+
+    out
+}
+
+fn print_json(extraction: &Extraction) {
+    println!("{}", build_json(extraction));
+}
+
+fn build_dot(extraction: &Extraction) -> String {
+    let mut out = String::from("digraph lineage {\n");
+    for name in extraction.tables.keys() {
+        out.push_str(&format!("  \"{}\";\n", name));
+    }
+    for (target, source) in &extraction.edges {
+        out.push_str(&format!("  \"{}\" -> \"{}\";\n", target, source));
+    }
+    out.push('}');
+    out
+}
+
+fn print_dot(extraction: &Extraction) {
+    println!("{}", build_dot(extraction));
+}
+
+fn print_columns(cols: &ColumnExtraction) {
+    for (table, column) in &cols.resolved {
+        println!("{}.{}", table, column);
+    }
+    if !cols.ambiguous.is_empty() {
+        println!("--- ambiguous ---");
+        for c in &cols.ambiguous {
+            println!("{}", c);
+        }
+    }
 }
 
 fn main() {
@@ -258,23 +1005,236 @@ fn main() {
         Ok(a) => a,
         Err(e) => {
             eprintln!(
-                "使い方: sqlparser --dialect <generic|postgres|mysql|mssql|snowflake|bigquery|sqlite|hive|ansi|redshift> (--file <path> | --sql \"...\")\nエラー: {}",
+                "使い方: sqlparser --dialect <generic|postgres|mysql|mssql|snowflake|bigquery|sqlite|hive|ansi|redshift|auto> [--probe] [--format <text|json|dot>] [--columns] (--file <path> | --sql \"...\")\nエラー: {}",
                 e
             );
             std::process::exit(2);
         }
     };
 
-    let statements = match parse_sql_with_dialect(&args.sql, args.dialect) {
-        Ok(stmts) => stmts,
-        Err(e) => {
-            eprintln!("SQLパースに失敗しました: {}", e);
-            std::process::exit(1);
-        }
+    if args.probe {
+        print_dialect_probe(&args.sql);
+        return;
+    }
+
+    let statements = match args.dialect {
+        DialectMode::Fixed(dialect) => match parse_sql_with_dialect(&args.sql, dialect) {
+            Ok(stmts) => stmts,
+            Err(e) => {
+                eprintln!("{}", format_parse_error(&args.source_label, &args.sql, dialect, &e));
+                std::process::exit(1);
+            }
+        },
+        DialectMode::Auto => match parse_sql_auto(&args.sql) {
+            Ok((dialect, stmts)) => {
+                eprintln!("検出された方言: {}", dialect_name(dialect));
+                stmts
+            }
+            Err(e) => {
+                // 全方言で失敗しているため、再トークナイズ用にはgenericを使う
+                eprintln!("{}", format_parse_error(&args.source_label, &args.sql, DialectKind::Generic, &e));
+                std::process::exit(1);
+            }
+        },
     };
 
-    let tables = extract_tables(&statements);
-    for t in tables {
-        println!("{}", t);
+    if args.columns {
+        let cols = extract_columns(&statements);
+        print_columns(&cols);
+        return;
+    }
+
+    let extraction = extract_tables(&statements);
+    match args.format {
+        OutputFormat::Text => print_text(&extraction),
+        OutputFormat::Json => print_json(&extraction),
+        OutputFormat::Dot => print_dot(&extraction),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn extract_for(sql: &str) -> Extraction {
+        let stmts = parse_sql_with_dialect(sql, DialectKind::Generic).unwrap();
+        extract_tables(&stmts)
+    }
+
+    fn extract_columns_for(sql: &str) -> ColumnExtraction {
+        let stmts = parse_sql_with_dialect(sql, DialectKind::Generic).unwrap();
+        extract_columns(&stmts)
+    }
+
+    #[test]
+    fn in_subquery_still_resolves_outer_column() {
+        let cols = extract_columns_for("SELECT a FROM t1 WHERE id IN (SELECT id FROM t2 WHERE x = 1)");
+        assert!(cols.resolved.contains(&("t1".to_string(), "a".to_string())));
+        assert!(cols.resolved.contains(&("t1".to_string(), "id".to_string())));
+    }
+
+    #[test]
+    fn bare_column_is_ambiguous_with_two_tables_in_scope() {
+        let cols = extract_columns_for("SELECT id FROM t1 JOIN t2 ON t1.id = t2.id");
+        assert!(cols.ambiguous.contains("id"));
+        assert!(!cols.resolved.iter().any(|(_, c)| c == "id"));
+    }
+
+    #[test]
+    fn qualified_column_resolves_via_alias_even_with_multiple_tables() {
+        let cols = extract_columns_for("SELECT a.x FROM t1 AS a JOIN t2 AS b ON a.id = b.id");
+        assert!(cols.resolved.contains(&("t1".to_string(), "x".to_string())));
+    }
+
+    #[test]
+    fn update_walks_assignment_value_subqueries() {
+        let ex = extract_for("UPDATE t SET x = (SELECT max(y) FROM other_table) WHERE id = 1");
+        assert_eq!(ex.tables.get("t"), Some(&TableRole::Write));
+        assert_eq!(ex.tables.get("other_table"), Some(&TableRole::Read));
+    }
+
+    #[test]
+    fn merge_matched_update_walks_assignment_value_subqueries() {
+        let ex = extract_for(
+            "MERGE INTO target USING source ON target.id = source.id \
+             WHEN MATCHED THEN UPDATE SET x = (SELECT max(y) FROM side_table)",
+        );
+        assert_eq!(ex.tables.get("target"), Some(&TableRole::Write));
+        assert_eq!(ex.tables.get("source"), Some(&TableRole::Read));
+        assert_eq!(ex.tables.get("side_table"), Some(&TableRole::Read));
+    }
+
+    #[test]
+    fn multi_table_delete_uses_tables_field_for_role() {
+        let ex = extract_for("DELETE t1, t2 FROM t1 JOIN t2 ON t1.id = t2.id WHERE t1.x = 1");
+        assert_eq!(ex.tables.get("t1"), Some(&TableRole::Write));
+        assert_eq!(ex.tables.get("t2"), Some(&TableRole::Write));
+    }
+
+    #[test]
+    fn build_json_reports_tables_edges_and_roles() {
+        let ex = extract_for("CREATE VIEW v AS SELECT * FROM t1");
+        let json = build_json(&ex);
+        assert!(json.contains("\"name\": \"t1\""));
+        assert!(json.contains("\"role\": \"read\""));
+        assert!(json.contains("\"target\": \"v\""));
+        assert!(json.contains("\"source\": \"t1\""));
+    }
+
+    #[test]
+    fn build_dot_renders_lineage_edge_from_target_to_source() {
+        let ex = extract_for("CREATE VIEW v AS SELECT * FROM t1");
+        let dot = build_dot(&ex);
+        assert!(dot.starts_with("digraph lineage {"));
+        assert!(dot.contains("\"t1\";"));
+        assert!(dot.contains("\"v\" -> \"t1\";"));
+    }
+
+    #[test]
+    fn recursive_cte_does_not_leak_as_physical_table() {
+        let ex = extract_for(
+            "WITH RECURSIVE r AS (SELECT 1 AS n UNION ALL SELECT n + 1 FROM r WHERE n < 5) \
+             SELECT * FROM r",
+        );
+        assert!(ex.ctes.contains("r"));
+        assert!(!ex.tables.contains_key("r"));
+        assert!(ex.tables.is_empty());
+    }
+
+    #[test]
+    fn inner_cte_shadows_outer_cte_of_the_same_name() {
+        let ex = extract_for(
+            "WITH x AS (SELECT * FROM t_outer) \
+             SELECT * FROM x, (WITH x AS (SELECT * FROM t_inner) SELECT * FROM x) AS sub",
+        );
+        assert!(ex.ctes.contains("x"));
+        assert!(!ex.tables.contains_key("x"));
+        assert_eq!(ex.tables.get("t_outer"), Some(&TableRole::Read));
+        assert_eq!(ex.tables.get("t_inner"), Some(&TableRole::Read));
+    }
+
+    #[test]
+    fn insert_values_walks_embedded_subqueries() {
+        let ex = extract_for("INSERT INTO a VALUES ((SELECT max(y) FROM side_table2))");
+        assert_eq!(ex.tables.get("a"), Some(&TableRole::Write));
+        assert_eq!(ex.tables.get("side_table2"), Some(&TableRole::Read));
+    }
+
+    // 実際に `parse_sql_with_dialect` を失敗させ、その本物のエラー文字列を診断整形にかける。
+    // TokenizerError（位置情報つき）とParserError（位置情報なし）の両方の経路を確認する
+    fn real_parse_error(sql: &str) -> String {
+        match parse_sql_with_dialect(sql, DialectKind::Generic) {
+            Ok(_) => panic!("expected a parse error for {:?}", sql),
+            Err(e) => e,
+        }
+    }
+
+    #[test]
+    fn tokenizer_error_location_is_extracted_directly() {
+        let err = real_parse_error("SELECT 'abc FROM t");
+        assert!(err.contains("Unterminated string literal"));
+        assert_eq!(extract_line_col(&err), Some((1, 8)));
+    }
+
+    #[test]
+    fn parser_error_has_no_embedded_location() {
+        // sqlparser 0.37のParserError::ParserErrorは位置情報を一切含まない。
+        // この前提が崩れたら再トークナイズのフォールバックを見直す必要がある
+        let err = real_parse_error("SELECT * FROM t WHERE");
+        assert!(extract_line_col(&err).is_none());
+    }
+
+    #[test]
+    fn locate_parse_error_falls_back_to_last_token_for_parser_errors() {
+        let sql = "SELECT * FROM t WHERE";
+        let err = real_parse_error(sql);
+        assert_eq!(locate_parse_error(sql, DialectKind::Generic, &err), Some((1, 17)));
+    }
+
+    #[test]
+    fn format_parse_error_draws_caret_for_a_real_parser_error() {
+        let sql = "SELECT * FROM t WHERE";
+        let err = real_parse_error(sql);
+        let formatted = format_parse_error("<sql>", sql, DialectKind::Generic, &err);
+        assert!(formatted.contains("<sql>:1:17:"));
+        assert!(formatted.contains(sql));
+        assert!(formatted.contains("                ^"));
+    }
+
+    #[test]
+    fn format_parse_error_draws_caret_for_a_real_tokenizer_error() {
+        let sql = "SELECT 'abc FROM t";
+        let err = real_parse_error(sql);
+        let formatted = format_parse_error("<sql>", sql, DialectKind::Generic, &err);
+        assert!(formatted.contains("<sql>:1:8:"));
+        assert!(formatted.contains("       ^"));
+    }
+
+    #[test]
+    fn dialect_from_str_and_dialect_name_round_trip_for_all_dialects() {
+        for &dialect in &ALL_DIALECTS {
+            let name = dialect_name(dialect);
+            assert_eq!(dialect_from_str(name), Ok(dialect));
+        }
+    }
+
+    #[test]
+    fn auto_dialect_picks_generic_for_plain_ansi_sql() {
+        let (dialect, stmts) = parse_sql_auto("SELECT a FROM t1").unwrap();
+        assert_eq!(dialect, DialectKind::Generic);
+        assert_eq!(stmts.len(), 1);
+    }
+
+    #[test]
+    fn auto_dialect_stops_at_generic_for_backtick_identifiers() {
+        // GenericDialectもバッククォート識別子を受け付けるため、先頭のgenericで確定する
+        let (dialect, _) = parse_sql_auto("SELECT * FROM `t1` WHERE `a` = 1").unwrap();
+        assert_eq!(dialect, DialectKind::Generic);
+    }
+
+    #[test]
+    fn auto_dialect_reports_failure_when_no_dialect_parses() {
+        let err = parse_sql_auto("SELECT FROM FROM SELECT (((").unwrap_err();
+        assert!(err.contains("どの方言でもパースできませんでした"));
     }
 }